@@ -0,0 +1,556 @@
+//! Daemon mode: turns the usual cron-driven one-shot run into a supervised
+//! set of long-lived workers, one per check category, each on its own
+//! configurable interval. A shared snapshot of the latest readings can be
+//! dumped on demand with `SIGUSR1`, and `SIGHUP` reloads the config file's
+//! thresholds without restarting the process.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use signal_hook::consts::{SIGHUP, SIGUSR1};
+use signal_hook::iterator::Signals;
+use sysinfo::{DiskExt, ProcessExt, System, SystemExt};
+
+use crate::{gossip, host_identity, local_gossip_addr, state, Config};
+#[cfg(feature = "metrics")]
+use crate::metrics;
+
+/// How far wall-clock time is allowed to drift ahead of the monotonic clock
+/// between resume-detector ticks before it's treated as a resume from
+/// suspend rather than ordinary scheduling jitter.
+const RESUME_JUMP_THRESHOLD: Duration = Duration::from_secs(30);
+const RESUME_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+struct Reading {
+    summary: String,
+    failing: bool,
+    checked_at: Instant,
+}
+
+type Snapshot = HashMap<&'static str, Reading>;
+
+const WORKER_NAMES: [&str; 5] = ["load", "disks", "memory", "process_checks", "backup"];
+
+pub fn run(config_path: String, initial_config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    let (hostname, ip_addr) = host_identity();
+    let config = Arc::new(RwLock::new(initial_config));
+    let snapshot: Arc<Mutex<Snapshot>> = Arc::new(Mutex::new(HashMap::new()));
+    let warmup_until: Arc<Mutex<Instant>> = Arc::new(Mutex::new(Instant::now()));
+    // The state file is a single JSON document shared by every worker; this
+    // serializes their read-modify-write cycles so two workers reconciling
+    // at the same instant can't clobber each other's update.
+    let state_lock: Arc<Mutex<()>> = Arc::new(Mutex::new(()));
+
+    spawn_signal_handler(config_path.clone(), Arc::clone(&config), Arc::clone(&snapshot))?;
+    spawn_resume_detector(Arc::clone(&config), Arc::clone(&warmup_until));
+    spawn_gossip(Arc::clone(&config), hostname.clone(), local_gossip_addr());
+
+    #[cfg(feature = "metrics")]
+    let gauges = spawn_metrics(Arc::clone(&config), hostname.clone());
+
+    let intervals = {
+        let config = config.read().unwrap();
+        [
+            config.load_average.interval_secs,
+            config.disks.interval_secs,
+            config.memory.interval_secs,
+            config.process_checks.interval_secs,
+            config.backup.interval_secs,
+        ]
+    };
+
+    let handles: Vec<_> = WORKER_NAMES
+        .iter()
+        .zip(intervals)
+        .map(|(&name, interval_secs)| {
+            let config = Arc::clone(&config);
+            let snapshot = Arc::clone(&snapshot);
+            let warmup_until = Arc::clone(&warmup_until);
+            let state_lock = Arc::clone(&state_lock);
+            let hostname = hostname.clone();
+            let ip_addr = ip_addr.clone();
+            let config_path = config_path.clone();
+            let interval = Duration::from_secs(interval_secs.max(1));
+            #[cfg(feature = "metrics")]
+            let gauges = gauges.clone();
+
+            thread::spawn(move || loop {
+                let reading = run_worker(
+                    name,
+                    &config,
+                    &warmup_until,
+                    &hostname,
+                    &ip_addr,
+                    &config_path,
+                    &state_lock,
+                    #[cfg(feature = "metrics")]
+                    gauges.as_ref(),
+                );
+                snapshot.lock().unwrap().insert(name, reading);
+                thread::sleep(interval);
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        let _ = handle.join();
+    }
+
+    Ok(())
+}
+
+/// Watches for a forward jump in wall-clock time that the monotonic clock
+/// didn't see — a laptop or VM waking from suspend — and, when one is
+/// found, grants the same warmup grace the load/memory workers get right
+/// after boot.
+fn spawn_resume_detector(config: Arc<RwLock<Config>>, warmup_until: Arc<Mutex<Instant>>) {
+    thread::spawn(move || {
+        let mut last_wall = SystemTime::now();
+        let mut last_monotonic = Instant::now();
+
+        loop {
+            thread::sleep(RESUME_CHECK_INTERVAL);
+
+            let now_wall = SystemTime::now();
+            let now_monotonic = Instant::now();
+            let wall_elapsed = now_wall.duration_since(last_wall).unwrap_or_default();
+            let monotonic_elapsed = now_monotonic.duration_since(last_monotonic);
+
+            if wall_elapsed > monotonic_elapsed + RESUME_JUMP_THRESHOLD {
+                let warmup_secs = config.read().unwrap().warmup_secs;
+                println!(
+                    "sysalert: wall clock jumped {}s ahead of the monotonic clock, likely a resume from suspend — applying {warmup_secs}s warmup grace",
+                    wall_elapsed.as_secs()
+                );
+                *warmup_until.lock().unwrap() = Instant::now() + Duration::from_secs(warmup_secs);
+            }
+
+            last_wall = now_wall;
+            last_monotonic = now_monotonic;
+        }
+    });
+}
+
+/// Runs the gossip-based peer liveness detector as one more daemon worker,
+/// so `--daemon` and `peers` can be configured together instead of gossip
+/// silently never starting. A no-op if `peers` is empty. `gossip_addr` is
+/// this host's LAN-routable address (see `local_gossip_addr()`), used to
+/// identify ourselves to peers when `gossip_bind` is a wildcard address.
+fn spawn_gossip(config: Arc<RwLock<Config>>, hostname: String, gossip_addr: Option<IpAddr>) {
+    let peers_configured = !config.read().unwrap().peers.is_empty();
+    if !peers_configured {
+        return;
+    }
+
+    thread::spawn(move || loop {
+        let current = config.read().unwrap().clone();
+        if let Err(e) = gossip::run(&current, &hostname, gossip_addr, &current.gossip_bind) {
+            eprintln!("sysalert: gossip failed, restarting: {e}");
+        }
+        thread::sleep(Duration::from_secs(5));
+    });
+}
+
+/// Builds a `Gauges` cell for the metrics exporter to serve and spawns the
+/// exporter against it, returning `None` (and spawning nothing) if metrics
+/// aren't configured. Workers update the returned cell directly off data
+/// they already fetched for their own checks, so no separate poller ever
+/// runs alongside the daemon's own.
+#[cfg(feature = "metrics")]
+fn spawn_metrics(config: Arc<RwLock<Config>>, hostname: String) -> Option<metrics::SharedGauges> {
+    let snapshot_config = config.read().unwrap().clone();
+    snapshot_config.metrics.as_ref()?;
+
+    let gauges: metrics::SharedGauges = Arc::new(Mutex::new(metrics::Gauges::default()));
+    let shared = Arc::clone(&gauges);
+    thread::spawn(move || {
+        if let Err(e) = metrics::run(snapshot_config, hostname, Some(shared)) {
+            eprintln!("sysalert: metrics exporter failed: {e}");
+        }
+    });
+    Some(gauges)
+}
+
+fn spawn_signal_handler(
+    config_path: String,
+    config: Arc<RwLock<Config>>,
+    snapshot: Arc<Mutex<Snapshot>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut signals = Signals::new([SIGUSR1, SIGHUP])?;
+
+    thread::spawn(move || {
+        for signal in signals.forever() {
+            match signal {
+                SIGUSR1 => print_status(&snapshot),
+                SIGHUP => reload_config(&config_path, &config),
+                _ => {}
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn print_status(snapshot: &Mutex<Snapshot>) {
+    let snapshot = snapshot.lock().unwrap();
+
+    println!("sysalert status ({} workers reporting):", snapshot.len());
+    for name in WORKER_NAMES {
+        match snapshot.get(name) {
+            Some(reading) => {
+                let status = if reading.failing { "FAIL" } else { "ok" };
+                println!(
+                    "  [{status}] {name}: {} ({:.0}s ago)",
+                    reading.summary,
+                    reading.checked_at.elapsed().as_secs_f64()
+                );
+            }
+            None => println!("  [?] {name}: no reading yet"),
+        }
+    }
+}
+
+fn reload_config(config_path: &str, config: &RwLock<Config>) {
+    match std::fs::read_to_string(config_path).and_then(|contents| {
+        toml::from_str::<Config>(&contents)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }) {
+        Ok(new_config) => {
+            *config.write().unwrap() = new_config;
+            println!("sysalert: reloaded config from {config_path}");
+        }
+        Err(e) => eprintln!("sysalert: failed to reload config: {e}"),
+    }
+}
+
+/// Runs one worker's check, reconciles its alerts (scoped to just the keys
+/// that worker owns, so another worker's still-failing key never looks
+/// "recovered" here), and returns the `Reading` for the status snapshot.
+#[allow(clippy::too_many_arguments)]
+fn run_worker(
+    name: &'static str,
+    config: &RwLock<Config>,
+    warmup_until: &Mutex<Instant>,
+    hostname: &str,
+    ip_addr: &str,
+    config_path: &str,
+    state_lock: &Mutex<()>,
+    #[cfg(feature = "metrics")] gauges: Option<&metrics::SharedGauges>,
+) -> Reading {
+    let config = config.read().unwrap();
+
+    let (summary, failing, scope, alerts) = match name {
+        "load" => {
+            let (summary, failing, alerts) = check_load(
+                &config,
+                in_warmup(&config, warmup_until),
+                #[cfg(feature = "metrics")]
+                gauges,
+            );
+            let scope = vec!["load1".to_string(), "load5".to_string(), "load15".to_string()];
+            (summary, failing, scope, alerts)
+        }
+        "disks" => {
+            let (summary, failing, alerts) = check_disks(
+                &config,
+                #[cfg(feature = "metrics")]
+                gauges,
+            );
+            let scope = config.disks.disks.iter().map(|mount| format!("disk:{mount}")).collect();
+            (summary, failing, scope, alerts)
+        }
+        "memory" => {
+            let (summary, failing, alerts) = check_memory(
+                &config,
+                in_warmup(&config, warmup_until),
+                #[cfg(feature = "metrics")]
+                gauges,
+            );
+            (summary, failing, vec!["memory".to_string()], alerts)
+        }
+        "process_checks" => {
+            let (summary, failing, alerts) = check_processes(
+                &config,
+                #[cfg(feature = "metrics")]
+                gauges,
+            );
+            let scope = vec![
+                "process:web_server".to_string(),
+                "process:mysql".to_string(),
+                "process:mysqld_memory".to_string(),
+                "process:mariadbd_memory".to_string(),
+                "process:b2".to_string(),
+            ];
+            (summary, failing, scope, alerts)
+        }
+        "backup" => {
+            let (summary, failing, alerts) = check_backup(
+                #[cfg(feature = "metrics")]
+                gauges,
+            );
+            (summary, failing, vec!["backup".to_string()], alerts)
+        }
+        _ => unreachable!("unknown worker {name}"),
+    };
+
+    {
+        let _guard = state_lock.lock().unwrap();
+        state::reconcile(&config, config_path, hostname, ip_addr, Some(&scope), &alerts);
+    }
+
+    Reading {
+        summary,
+        failing,
+        checked_at: Instant::now(),
+    }
+}
+
+fn in_warmup(config: &Config, warmup_until: &Mutex<Instant>) -> bool {
+    // `uptime()` reads straight from the OS and needs no refresh, so an
+    // empty `System` is enough here -- no need to pay for `new_all()`.
+    System::new().uptime() < config.warmup_secs || Instant::now() < *warmup_until.lock().unwrap()
+}
+
+fn check_load(
+    config: &Config,
+    in_warmup: bool,
+    #[cfg(feature = "metrics")] gauges: Option<&metrics::SharedGauges>,
+) -> (String, bool, Vec<(String, String)>) {
+    let mut s = System::new();
+    s.refresh_cpu();
+    let load = s.load_average();
+
+    #[cfg(feature = "metrics")]
+    if let Some(gauges) = gauges {
+        let mut gauges = gauges.lock().unwrap();
+        gauges.load = (load.one, load.five, load.fifteen);
+        gauges.uptime_secs = s.uptime();
+    }
+
+    let mut alerts = Vec::new();
+
+    let load1_limit = config.load_average.one * 2.0;
+    if load.one > load1_limit {
+        alerts.push((
+            "load1".to_string(),
+            format!("`🚨 load 1: {:.4} > {load1_limit}`", load.one),
+        ));
+    }
+
+    // load-1 stays critical through warmup; load-5/load-15 are downgraded.
+    if !in_warmup {
+        if load.five > config.load_average.five {
+            alerts.push((
+                "load5".to_string(),
+                format!("`load 5: {:.4} > {}`", load.five, config.load_average.five),
+            ));
+        }
+        if load.fifteen > config.load_average.fifteen {
+            alerts.push((
+                "load15".to_string(),
+                format!("`load 15: {:.4} > {}`", load.fifteen, config.load_average.fifteen),
+            ));
+        }
+    }
+
+    let failing = !alerts.is_empty();
+    let summary = if in_warmup {
+        format!(
+            "load {:.2}/{:.2}/{:.2} (warming up)",
+            load.one, load.five, load.fifteen
+        )
+    } else {
+        format!("load {:.2}/{:.2}/{:.2}", load.one, load.five, load.fifteen)
+    };
+
+    (summary, failing, alerts)
+}
+
+fn check_disks(
+    config: &Config,
+    #[cfg(feature = "metrics")] gauges: Option<&metrics::SharedGauges>,
+) -> (String, bool, Vec<(String, String)>) {
+    let mut s = System::new();
+    s.refresh_disks_list();
+    s.refresh_disks();
+
+    let mut failing_mounts = Vec::new();
+    let mut alerts = Vec::new();
+    #[cfg(feature = "metrics")]
+    let mut disk_free_ratio = Vec::new();
+    for d in s.disks() {
+        let mount = d.mount_point().to_string_lossy().to_string();
+        if config.disks.disks.contains(&mount) {
+            let perc_free = d.available_space() as f64 / d.total_space() as f64;
+            #[cfg(feature = "metrics")]
+            disk_free_ratio.push((mount.clone(), perc_free));
+            if perc_free < config.disks.minimum {
+                failing_mounts.push(format!("{mount}: {perc_free:.4}"));
+                alerts.push((
+                    format!("disk:{mount}"),
+                    format!("`{mount}: {perc_free:.4} < {}`", config.disks.minimum),
+                ));
+            }
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    if let Some(gauges) = gauges {
+        gauges.lock().unwrap().disk_free_ratio = disk_free_ratio;
+    }
+
+    let failing = !failing_mounts.is_empty();
+    let summary = if failing {
+        failing_mounts.join(", ")
+    } else {
+        format!("{} mounts within limits", config.disks.disks.len())
+    };
+
+    (summary, failing, alerts)
+}
+
+fn check_memory(
+    config: &Config,
+    in_warmup: bool,
+    #[cfg(feature = "metrics")] gauges: Option<&metrics::SharedGauges>,
+) -> (String, bool, Vec<(String, String)>) {
+    let mut s = System::new();
+    s.refresh_memory();
+
+    let perc_free = if s.available_memory() as f64 == 0.0 {
+        (s.total_memory() - s.used_memory()) as f64 / s.total_memory() as f64
+    } else {
+        s.available_memory() as f64 / s.total_memory() as f64
+    };
+
+    #[cfg(feature = "metrics")]
+    if let Some(gauges) = gauges {
+        gauges.lock().unwrap().memory_free_ratio = perc_free;
+    }
+
+    let failing = !in_warmup && perc_free < config.memory.minimum;
+    let alerts = if failing {
+        vec![(
+            "memory".to_string(),
+            format!("`memory: {perc_free:.4} < {}`", config.memory.minimum),
+        )]
+    } else {
+        Vec::new()
+    };
+
+    let summary = if in_warmup {
+        format!("{perc_free:.4} free (warming up)")
+    } else {
+        format!("{perc_free:.4} free")
+    };
+
+    (summary, failing, alerts)
+}
+
+fn check_processes(
+    config: &Config,
+    #[cfg(feature = "metrics")] gauges: Option<&metrics::SharedGauges>,
+) -> (String, bool, Vec<(String, String)>) {
+    let mut s = System::new();
+    s.refresh_processes();
+
+    #[cfg(feature = "metrics")]
+    if let Some(gauges) = gauges {
+        let mut gauges = gauges.lock().unwrap();
+        gauges.process_running = metrics::WATCHED_PROCESSES
+            .iter()
+            .map(|&name| (name, s.processes_by_name(name).count() > 0))
+            .collect();
+        gauges.b2_process_count = s.processes().values().filter(|p| p.name().contains("b2")).count();
+    }
+
+    let mut failures = Vec::new();
+    let mut alerts = Vec::new();
+
+    if !config.process_checks.disable_web_server_check
+        && !process_running(&s, &["apache2", "nginx"])
+    {
+        failures.push("web server not running".to_string());
+        alerts.push(("process:web_server".to_string(), "`web server not running`".to_string()));
+    }
+
+    if !config.process_checks.disable_mysql_check && !process_running(&s, &["mariadbd", "mysqld"])
+    {
+        failures.push("mysql not running".to_string());
+        alerts.push(("process:mysql".to_string(), "`mysql not running`".to_string()));
+    }
+
+    if !config.process_checks.disable_mysql_memory_check {
+        let limit = (s.total_memory() as f64 * 0.75) as u64;
+        for name in ["mysqld", "mariadbd"] {
+            for process in s.processes_by_name(name) {
+                if process.memory() > limit {
+                    failures.push(format!("{name} using {} bytes", process.memory()));
+                    let key = if name == "mysqld" {
+                        "process:mysqld_memory"
+                    } else {
+                        "process:mariadbd_memory"
+                    };
+                    alerts.push((key.to_string(), format!("`{name} using {} bytes`", process.memory())));
+                }
+            }
+        }
+    }
+
+    let b2_count = s.processes().values().filter(|p| p.name().contains("b2")).count();
+    if b2_count > 1 {
+        failures.push(format!("b2 running {b2_count} times"));
+        alerts.push(("process:b2".to_string(), format!("`b2 is running {b2_count} times`")));
+    }
+
+    let failing = !failures.is_empty();
+    let summary = if failing {
+        failures.join(", ")
+    } else {
+        "all processes healthy".to_string()
+    };
+
+    (summary, failing, alerts)
+}
+
+fn process_running(s: &System, names: &[&str]) -> bool {
+    names.iter().any(|name| s.processes_by_name(name).count() > 0)
+}
+
+fn check_backup(
+    #[cfg(feature = "metrics")] gauges: Option<&metrics::SharedGauges>,
+) -> (String, bool, Vec<(String, String)>) {
+    let backup_file = "/tmp/backup.heartbeat";
+    let elapsed = std::fs::metadata(backup_file)
+        .and_then(|m| m.modified())
+        .and_then(|modified| modified.elapsed().map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e)));
+
+    #[cfg(feature = "metrics")]
+    if let Some(gauges) = gauges {
+        gauges.lock().unwrap().backup_heartbeat_age_secs = elapsed.as_ref().ok().map(|e| e.as_secs());
+    }
+
+    match elapsed {
+        Ok(elapsed) => {
+            let failing = elapsed.as_secs() > ((60 * 60 * 24) + (60 * 15));
+            let alerts = if failing {
+                vec![(
+                    "backup".to_string(),
+                    format!("`backup heartbeat is {}s old`", elapsed.as_secs()),
+                )]
+            } else {
+                Vec::new()
+            };
+            (format!("last heartbeat {}s ago", elapsed.as_secs()), failing, alerts)
+        }
+        Err(e) => (
+            format!("heartbeat error: {e}"),
+            true,
+            vec![("backup".to_string(), format!("`heartbeat error: {e}`"))],
+        ),
+    }
+}
@@ -0,0 +1,618 @@
+//! A small SWIM-style failure detector used for peer-to-peer liveness checks.
+//!
+//! Instances configured with `peers` in their TOML gossip over UDP: on a
+//! fixed interval each instance pings one random member, falls back to
+//! indirect probes via `k` other members if the ping is unanswered, and
+//! piggybacks recent membership changes on every message so the cluster
+//! converges without a central coordinator.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{send_telegram, Config};
+
+const PROBE_INTERVAL: Duration = Duration::from_secs(5);
+const ACK_TIMEOUT: Duration = Duration::from_secs(2);
+const INDIRECT_PROBE_COUNT: usize = 3;
+const SUSPECT_TIMEOUT: Duration = Duration::from_secs(15);
+const MAX_PIGGYBACKED_UPDATES: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MemberState {
+    Alive,
+    Suspect,
+    Dead,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MembershipUpdate {
+    addr: SocketAddr,
+    state: MemberState,
+    incarnation: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Message {
+    Ping {
+        from: SocketAddr,
+        updates: Vec<MembershipUpdate>,
+    },
+    Ack {
+        from: SocketAddr,
+        /// Set when this `Ack` is a helper relaying the result of an
+        /// indirect probe — the helper reached `on_behalf_of` directly, but
+        /// `from` is still the helper's own address.
+        on_behalf_of: Option<SocketAddr>,
+        updates: Vec<MembershipUpdate>,
+    },
+    PingReq {
+        from: SocketAddr,
+        target: SocketAddr,
+        updates: Vec<MembershipUpdate>,
+    },
+}
+
+struct Member {
+    state: MemberState,
+    incarnation: u64,
+    suspected_at: Option<Instant>,
+}
+
+struct Detector {
+    socket: UdpSocket,
+    local_addr: SocketAddr,
+    members: HashMap<SocketAddr, Member>,
+    pending_updates: Vec<MembershipUpdate>,
+}
+
+impl Detector {
+    fn new(socket: UdpSocket, local_addr: SocketAddr, peers: &[SocketAddr]) -> Self {
+        let mut members = HashMap::new();
+        for &addr in peers {
+            members.insert(
+                addr,
+                Member {
+                    state: MemberState::Alive,
+                    incarnation: 0,
+                    suspected_at: None,
+                },
+            );
+        }
+
+        Self {
+            socket,
+            local_addr,
+            members,
+            pending_updates: Vec::new(),
+        }
+    }
+
+    fn queue_update(&mut self, addr: SocketAddr, state: MemberState, incarnation: u64) {
+        self.pending_updates.push(MembershipUpdate {
+            addr,
+            state,
+            incarnation,
+        });
+        if self.pending_updates.len() > MAX_PIGGYBACKED_UPDATES {
+            self.pending_updates.remove(0);
+        }
+    }
+
+    fn drain_updates(&mut self) -> Vec<MembershipUpdate> {
+        std::mem::take(&mut self.pending_updates)
+    }
+
+    fn apply_updates(&mut self, config: &Config, hostname: &str, updates: Vec<MembershipUpdate>) {
+        for update in updates {
+            if update.addr == self.local_addr {
+                continue;
+            }
+
+            let member = self.members.entry(update.addr).or_insert(Member {
+                state: MemberState::Alive,
+                incarnation: 0,
+                suspected_at: None,
+            });
+
+            // Only a strictly higher incarnation (or same incarnation going
+            // to a "more dead" state) is allowed to override what we know.
+            let deadness = |state: MemberState| match state {
+                MemberState::Alive => 0,
+                MemberState::Suspect => 1,
+                MemberState::Dead => 2,
+            };
+            if update.incarnation < member.incarnation
+                || (update.incarnation == member.incarnation
+                    && deadness(update.state) < deadness(member.state))
+            {
+                continue;
+            }
+
+            // If we already knew exactly this, re-queuing it would keep it
+            // piggybacking forever; only re-gossip updates that are actually
+            // news to us, so each one dies out once the cluster converges.
+            let is_news = update.incarnation > member.incarnation || update.state != member.state;
+
+            let was_alive = member.state == MemberState::Alive;
+            member.state = update.state;
+            member.incarnation = update.incarnation;
+            member.suspected_at = match update.state {
+                MemberState::Suspect => Some(Instant::now()),
+                _ => None,
+            };
+
+            if is_news {
+                self.queue_update(update.addr, update.state, update.incarnation);
+            }
+
+            if was_alive && update.state == MemberState::Dead {
+                send_telegram(
+                    config,
+                    format!("\u{1F480} `{hostname}` lost contact with peer `{}`", update.addr),
+                );
+            }
+        }
+    }
+
+    fn send_to(&self, addr: SocketAddr, message: &Message) {
+        if let Ok(bytes) = serde_json::to_vec(message) {
+            let _ = self.socket.send_to(&bytes, addr);
+        }
+    }
+
+    fn probe_random_member(&mut self, config: &Config, hostname: &str) {
+        let candidates: Vec<SocketAddr> = self.members.keys().copied().collect();
+        if candidates.is_empty() {
+            return;
+        }
+
+        let target = candidates[fastrand::usize(..candidates.len())];
+
+        let updates = self.drain_updates();
+        self.send_to(
+            target,
+            &Message::Ping {
+                from: self.local_addr,
+                updates,
+            },
+        );
+
+        if self.await_ack(target, ACK_TIMEOUT, config, hostname) {
+            return;
+        }
+
+        // Direct probe failed; ask k other members to probe indirectly.
+        let mut indirect_targets: Vec<SocketAddr> = candidates
+            .into_iter()
+            .filter(|addr| *addr != target)
+            .collect();
+        fastrand::shuffle(&mut indirect_targets);
+        indirect_targets.truncate(INDIRECT_PROBE_COUNT);
+
+        for helper in &indirect_targets {
+            self.send_to(
+                *helper,
+                &Message::PingReq {
+                    from: self.local_addr,
+                    target,
+                    updates: Vec::new(),
+                },
+            );
+        }
+
+        if !indirect_targets.is_empty() && self.await_ack(target, ACK_TIMEOUT, config, hostname) {
+            return;
+        }
+
+        self.mark_suspect(config, hostname, target);
+    }
+
+    /// Blocks for up to `timeout` waiting for an `Ack` from `target`,
+    /// processing any other messages that arrive in the meantime.
+    fn await_ack(
+        &mut self,
+        target: SocketAddr,
+        timeout: Duration,
+        config: &Config,
+        hostname: &str,
+    ) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut buf = [0u8; 2048];
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            let _ = self.socket.set_read_timeout(Some(remaining));
+
+            match self.socket.recv_from(&mut buf) {
+                Ok((n, _)) => {
+                    if let Ok(message) = serde_json::from_slice::<Message>(&buf[..n]) {
+                        let acked = self.handle_message(config, hostname, message, target);
+                        if acked {
+                            return true;
+                        }
+                    }
+                }
+                Err(_) => return false,
+            }
+        }
+    }
+
+    /// Handles an inbound message, returning true if it was an `Ack` proving
+    /// `waiting_on` is reachable — either directly (`from == waiting_on`) or
+    /// relayed by a helper that reached it on our behalf.
+    fn handle_message(
+        &mut self,
+        config: &Config,
+        hostname: &str,
+        message: Message,
+        waiting_on: SocketAddr,
+    ) -> bool {
+        match message {
+            Message::Ping { from, updates } => {
+                self.apply_updates(config, hostname, updates);
+                let reply_updates = self.drain_updates();
+                self.send_to(
+                    from,
+                    &Message::Ack {
+                        from: self.local_addr,
+                        on_behalf_of: None,
+                        updates: reply_updates,
+                    },
+                );
+                false
+            }
+            Message::Ack {
+                from,
+                on_behalf_of,
+                updates,
+            } => {
+                self.apply_updates(config, hostname, updates);
+                self.refute_suspicion(from);
+                if let Some(reached) = on_behalf_of {
+                    self.refute_suspicion(reached);
+                }
+                from == waiting_on || on_behalf_of == Some(waiting_on)
+            }
+            Message::PingReq {
+                from,
+                target,
+                updates,
+            } => {
+                self.apply_updates(config, hostname, updates);
+                self.send_to(
+                    target,
+                    &Message::Ping {
+                        from: self.local_addr,
+                        updates: Vec::new(),
+                    },
+                );
+                if self.await_ack(target, ACK_TIMEOUT, config, hostname) {
+                    self.send_to(
+                        from,
+                        &Message::Ack {
+                            from: self.local_addr,
+                            on_behalf_of: Some(target),
+                            updates: Vec::new(),
+                        },
+                    );
+                }
+                false
+            }
+        }
+    }
+
+    fn refute_suspicion(&mut self, addr: SocketAddr) {
+        if let Some(member) = self.members.get_mut(&addr) {
+            if member.state != MemberState::Alive {
+                member.incarnation += 1;
+                member.state = MemberState::Alive;
+                member.suspected_at = None;
+                self.queue_update(addr, MemberState::Alive, member.incarnation);
+            }
+        }
+    }
+
+    fn mark_suspect(&mut self, config: &Config, hostname: &str, addr: SocketAddr) {
+        if let Some(member) = self.members.get_mut(&addr) {
+            if member.state == MemberState::Alive {
+                member.state = MemberState::Suspect;
+                member.suspected_at = Some(Instant::now());
+                self.queue_update(addr, MemberState::Suspect, member.incarnation);
+            }
+        }
+        let _ = (config, hostname);
+    }
+
+    fn expire_suspects(&mut self, config: &Config, hostname: &str) {
+        let now = Instant::now();
+        let mut newly_dead = Vec::new();
+
+        for (addr, member) in self.members.iter_mut() {
+            if member.state == MemberState::Suspect {
+                if let Some(since) = member.suspected_at {
+                    if now.duration_since(since) >= SUSPECT_TIMEOUT {
+                        member.state = MemberState::Dead;
+                        member.incarnation += 1;
+                        newly_dead.push((*addr, member.incarnation));
+                    }
+                }
+            }
+        }
+
+        for (addr, incarnation) in newly_dead {
+            self.queue_update(addr, MemberState::Dead, incarnation);
+            send_telegram(
+                config,
+                format!("\u{1F480} `{hostname}` believes peer `{addr}` is dead"),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{default_gossip_bind, default_warmup_secs};
+
+    fn test_config() -> Config {
+        Config {
+            telegram_token: String::new(),
+            telegram_chat_id: String::new(),
+            disable_self_update: false,
+            memory: Default::default(),
+            disks: Default::default(),
+            load_average: Default::default(),
+            process_checks: Default::default(),
+            peers: Vec::new(),
+            gossip_bind: default_gossip_bind(),
+            backup: Default::default(),
+            renotify_after_secs: HashMap::new(),
+            warmup_secs: default_warmup_secs(),
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+
+    fn test_detector() -> Detector {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let local_addr = socket.local_addr().unwrap();
+        Detector::new(socket, local_addr, &[])
+    }
+
+    fn other_addr() -> SocketAddr {
+        "127.0.0.1:9".parse().unwrap()
+    }
+
+    #[test]
+    fn apply_updates_learns_an_unknown_member() {
+        let mut detector = test_detector();
+        let addr = other_addr();
+
+        detector.apply_updates(
+            &test_config(),
+            "host",
+            vec![MembershipUpdate {
+                addr,
+                state: MemberState::Suspect,
+                incarnation: 1,
+            }],
+        );
+
+        let member = &detector.members[&addr];
+        assert_eq!(member.state, MemberState::Suspect);
+        assert_eq!(member.incarnation, 1);
+        // News about a previously-unknown member must re-propagate so it
+        // reaches the rest of the cluster, not just us.
+        assert_eq!(detector.pending_updates.len(), 1);
+    }
+
+    #[test]
+    fn apply_updates_ignores_stale_incarnation() {
+        let mut detector = test_detector();
+        let addr = other_addr();
+        detector.members.insert(
+            addr,
+            Member {
+                state: MemberState::Alive,
+                incarnation: 5,
+                suspected_at: None,
+            },
+        );
+
+        detector.apply_updates(
+            &test_config(),
+            "host",
+            vec![MembershipUpdate {
+                addr,
+                state: MemberState::Dead,
+                incarnation: 3,
+            }],
+        );
+
+        let member = &detector.members[&addr];
+        assert_eq!(member.state, MemberState::Alive);
+        assert_eq!(member.incarnation, 5);
+        assert!(detector.pending_updates.is_empty());
+    }
+
+    #[test]
+    fn apply_updates_does_not_requeue_already_known_state() {
+        let mut detector = test_detector();
+        let addr = other_addr();
+        detector.members.insert(
+            addr,
+            Member {
+                state: MemberState::Alive,
+                incarnation: 2,
+                suspected_at: None,
+            },
+        );
+
+        // Same incarnation, same state: not news, so it shouldn't be queued
+        // to piggyback again -- otherwise a converged update never dies out.
+        detector.apply_updates(
+            &test_config(),
+            "host",
+            vec![MembershipUpdate {
+                addr,
+                state: MemberState::Alive,
+                incarnation: 2,
+            }],
+        );
+
+        assert!(detector.pending_updates.is_empty());
+    }
+
+    #[test]
+    fn apply_updates_ignores_updates_about_ourselves() {
+        let mut detector = test_detector();
+        let local_addr = detector.local_addr;
+
+        detector.apply_updates(
+            &test_config(),
+            "host",
+            vec![MembershipUpdate {
+                addr: local_addr,
+                state: MemberState::Dead,
+                incarnation: 1,
+            }],
+        );
+
+        assert!(!detector.members.contains_key(&local_addr));
+        assert!(detector.pending_updates.is_empty());
+    }
+
+    #[test]
+    fn expire_suspects_promotes_overdue_suspect_to_dead() {
+        let mut detector = test_detector();
+        let addr = other_addr();
+        detector.members.insert(
+            addr,
+            Member {
+                state: MemberState::Suspect,
+                incarnation: 1,
+                suspected_at: Some(Instant::now() - SUSPECT_TIMEOUT - Duration::from_secs(1)),
+            },
+        );
+
+        detector.expire_suspects(&test_config(), "host");
+
+        let member = &detector.members[&addr];
+        assert_eq!(member.state, MemberState::Dead);
+        assert_eq!(member.incarnation, 2);
+    }
+
+    #[test]
+    fn expire_suspects_leaves_recent_suspect_alone() {
+        let mut detector = test_detector();
+        let addr = other_addr();
+        detector.members.insert(
+            addr,
+            Member {
+                state: MemberState::Suspect,
+                incarnation: 1,
+                suspected_at: Some(Instant::now()),
+            },
+        );
+
+        detector.expire_suspects(&test_config(), "host");
+
+        let member = &detector.members[&addr];
+        assert_eq!(member.state, MemberState::Suspect);
+        assert_eq!(member.incarnation, 1);
+    }
+
+    #[test]
+    fn routable_local_addr_swaps_in_a_private_gossip_addr() {
+        // The typical deployment: a wildcard bind and a LAN-private
+        // self-address. This must not fall back to the wildcard just
+        // because the address isn't globally routable.
+        let bound_addr: SocketAddr = "0.0.0.0:7946".parse().unwrap();
+        let gossip_addr: IpAddr = "192.168.1.50".parse().unwrap();
+
+        let resolved = routable_local_addr(bound_addr, Some(gossip_addr));
+
+        assert_eq!(resolved, "192.168.1.50:7946".parse().unwrap());
+    }
+
+    #[test]
+    fn routable_local_addr_leaves_a_concrete_bind_alone() {
+        let bound_addr: SocketAddr = "10.0.0.5:7946".parse().unwrap();
+        let gossip_addr: IpAddr = "192.168.1.50".parse().unwrap();
+
+        let resolved = routable_local_addr(bound_addr, Some(gossip_addr));
+
+        assert_eq!(resolved, bound_addr);
+    }
+
+    #[test]
+    fn routable_local_addr_falls_back_to_the_wildcard_without_a_gossip_addr() {
+        let bound_addr: SocketAddr = "0.0.0.0:7946".parse().unwrap();
+
+        let resolved = routable_local_addr(bound_addr, None);
+
+        assert_eq!(resolved, bound_addr);
+    }
+}
+
+fn resolve_peers(peers: &[String]) -> Vec<SocketAddr> {
+    peers
+        .iter()
+        .filter_map(|peer| peer.to_socket_addrs().ok()?.next())
+        .collect()
+}
+
+/// `bind_addr`'s documented default is the wildcard `0.0.0.0`, and
+/// `UdpSocket::local_addr()` echoes that straight back — sent to peers in a
+/// `Ping`/`Ack`'s `from` field as "this is who I am, ack me here" it's
+/// unroutable, so every ack times out and every peer looks dead within one
+/// suspect timeout. Swap in the host's actual LAN-routable address (already
+/// resolved by the caller via `local_gossip_addr()`) whenever the bound
+/// address isn't already a concrete one. Unlike the address used to label
+/// Telegram alerts, this must accept private (RFC1918) addresses — the
+/// normal case for a cluster on a LAN/VPC — so it deliberately isn't
+/// `host_identity()`'s globally-routable-only pick.
+fn routable_local_addr(bound_addr: SocketAddr, gossip_addr: Option<IpAddr>) -> SocketAddr {
+    if !bound_addr.ip().is_unspecified() {
+        return bound_addr;
+    }
+
+    gossip_addr
+        .map(|ip| SocketAddr::new(ip, bound_addr.port()))
+        .unwrap_or(bound_addr)
+}
+
+/// Runs the gossip-based failure detector forever, or returns `Ok(())` at
+/// once if none of `config.peers` resolves to an address. Intended to be called
+/// from a long-lived process (daemon mode or a dedicated thread), not a
+/// cron-driven one-shot run. `gossip_addr` is this host's LAN-routable
+/// address (as returned by `local_gossip_addr()`), used to identify
+/// ourselves to peers when `bind_addr` is a wildcard address.
+pub fn run(
+    config: &Config,
+    hostname: &str,
+    gossip_addr: Option<IpAddr>,
+    bind_addr: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let peers = resolve_peers(&config.peers);
+    if peers.is_empty() {
+        return Ok(());
+    }
+
+    let socket = UdpSocket::bind(bind_addr)?;
+    let local_addr = routable_local_addr(socket.local_addr()?, gossip_addr);
+    let mut detector = Detector::new(socket, local_addr, &peers);
+
+    loop {
+        detector.probe_random_member(config, hostname);
+        detector.expire_suspects(config, hostname);
+        thread::sleep(PROBE_INTERVAL);
+    }
+}
@@ -1,5 +1,12 @@
 #![feature(ip)]
 
+mod daemon;
+mod gossip;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod state;
+mod wizard;
+
 use std::fs;
 use std::net::IpAddr;
 
@@ -9,7 +16,7 @@ use serde::Deserialize;
 use std::collections::HashMap;
 use sysinfo::{DiskExt, ProcessExt, System, SystemExt};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(deny_unknown_fields)]
 struct Config {
     telegram_token: String,
@@ -24,9 +31,84 @@ struct Config {
     load_average: LoadAverage,
     #[serde(default)]
     process_checks: ProcessChecks,
+    /// Other `host:port` addresses running sysalert to gossip liveness with.
+    /// When non-empty, sysalert runs a long-lived SWIM-style failure
+    /// detector alongside its regular checks instead of exiting immediately.
+    /// Requires `--daemon` — gossip's loop never exits, so it doesn't fit a
+    /// cron-driven one-shot run.
+    #[serde(default)]
+    peers: Vec<String>,
+    /// The local `host:port` the gossip listener binds to.
+    #[serde(default = "default_gossip_bind")]
+    gossip_bind: String,
+    #[serde(default)]
+    backup: Backup,
+    /// Per-check-key override (e.g. `"disk:/" = 21600`) for how often a
+    /// still-failing check should be re-notified, in seconds. Checks with
+    /// no entry here are only reported once, on the transition to failing,
+    /// until they recover.
+    #[serde(default)]
+    renotify_after_secs: HashMap<String, u64>,
+    /// How long after boot (or after a detected resume-from-suspend) to
+    /// skip the non-critical threshold checks, since load average and
+    /// memory are routinely abnormal while services are still starting.
+    #[serde(default = "default_warmup_secs")]
+    warmup_secs: u64,
+    /// Serves the same gauges the checks above compute over a Prometheus
+    /// text-exposition HTTP endpoint. Only present when built with the
+    /// `metrics` feature.
+    #[cfg(feature = "metrics")]
+    #[serde(default)]
+    metrics: Option<metrics::MetricsConfig>,
 }
 
-#[derive(Debug, Default, Deserialize)]
+fn default_gossip_bind() -> String {
+    "0.0.0.0:7946".to_string()
+}
+
+/// Detects this host's name and first global IPv4 address, used to label
+/// Telegram alerts the same way regardless of whether we're in a one-shot
+/// `run_checks` run or a daemon worker. Not suitable for gossip
+/// self-identity: see `local_gossip_addr()` for that.
+fn host_identity() -> (String, String) {
+    let s = System::new_all();
+    let hostname = s.host_name().unwrap_or_else(|| "unknown".to_string());
+
+    let ip_addr = if let Ok(ifas) = list_afinet_netifas() {
+        if let Some((_, ipaddr)) = ifas
+            .iter()
+            .find(|(_, ipaddr)| ipaddr.is_global() && matches!(ipaddr, IpAddr::V4(_)))
+        {
+            format!("{ipaddr:?}")
+        } else {
+            "unknown".to_owned()
+        }
+    } else {
+        "unknown".to_owned()
+    };
+
+    (hostname, ip_addr)
+}
+
+/// Picks a LAN-routable IPv4 address for gossip self-identification.
+/// `host_identity()`'s address is filtered to globally-routable addresses
+/// for Telegram display, which excludes every RFC1918 private address —
+/// exactly the common case for a cluster on a LAN/VPC — so gossip needs its
+/// own, more permissive pick: the first non-loopback IPv4 address on any
+/// interface, private or public.
+fn local_gossip_addr() -> Option<IpAddr> {
+    list_afinet_netifas()
+        .ok()?
+        .into_iter()
+        .map(|(_, ipaddr)| ipaddr)
+        .find(|ipaddr| matches!(ipaddr, IpAddr::V4(v4) if !v4.is_loopback()))
+}
+
+fn default_warmup_secs() -> u64 {
+    300
+}
+
+#[derive(Debug, Clone, Deserialize)]
 #[serde(deny_unknown_fields)]
 struct ProcessChecks {
     #[serde(default)]
@@ -35,9 +117,43 @@ struct ProcessChecks {
     disable_mysql_memory_check: bool,
     #[serde(default)]
     disable_web_server_check: bool,
+    #[serde(default = "default_worker_interval_secs")]
+    interval_secs: u64,
+}
+
+impl Default for ProcessChecks {
+    fn default() -> Self {
+        Self {
+            disable_mysql_check: false,
+            disable_mysql_memory_check: false,
+            disable_web_server_check: false,
+            interval_secs: default_worker_interval_secs(),
+        }
+    }
+}
+
+/// Default per-worker interval used by daemon mode; each check category can
+/// override it with its own `interval_secs` in the TOML.
+fn default_worker_interval_secs() -> u64 {
+    60
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Backup {
+    #[serde(default = "default_worker_interval_secs")]
+    interval_secs: u64,
+}
+
+impl Default for Backup {
+    fn default() -> Self {
+        Self {
+            interval_secs: default_worker_interval_secs(),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(deny_unknown_fields)]
 struct LoadAverage {
     #[serde(default = "default_load_average")]
@@ -46,6 +162,8 @@ struct LoadAverage {
     five: f64,
     #[serde(default = "default_load_average")]
     fifteen: f64,
+    #[serde(default = "default_worker_interval_secs")]
+    interval_secs: u64,
 }
 
 fn default_load_average() -> f64 {
@@ -62,17 +180,20 @@ impl Default for LoadAverage {
             one: value,
             five: value,
             fifteen: value,
+            interval_secs: default_worker_interval_secs(),
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(deny_unknown_fields)]
 struct Disks {
     #[serde(default = "default_disks")]
     disks: Vec<String>,
     #[serde(default = "default_disks_minimum")]
     minimum: f64,
+    #[serde(default = "default_worker_interval_secs")]
+    interval_secs: u64,
 }
 
 fn default_disks() -> Vec<String> {
@@ -88,15 +209,18 @@ impl Default for Disks {
         Self {
             disks: default_disks(),
             minimum: default_disks_minimum(),
+            interval_secs: default_worker_interval_secs(),
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(deny_unknown_fields)]
 struct Memory {
     #[serde(default = "default_memory_minimum")]
     minimum: f64,
+    #[serde(default = "default_worker_interval_secs")]
+    interval_secs: u64,
 }
 
 fn default_memory_minimum() -> f64 {
@@ -107,6 +231,7 @@ impl Default for Memory {
     fn default() -> Self {
         Self {
             minimum: default_memory_minimum(),
+            interval_secs: default_worker_interval_secs(),
         }
     }
 }
@@ -133,26 +258,64 @@ fn send_telegram(config: &Config, message: String) {
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let config_file = std::env::var("CONFIG").unwrap_or_else(|_| "sysalert.toml".to_string());
-    let config: Config = toml::from_str(&std::fs::read_to_string(config_file)?)?;
+
+    if std::env::args().any(|arg| arg == "--check-config") {
+        return wizard::check_config(&config_file);
+    }
+
+    if std::env::args().any(|arg| arg == "--wizard") || !std::path::Path::new(&config_file).exists()
+    {
+        return wizard::run(&config_file);
+    }
+
+    let config: Config = toml::from_str(&std::fs::read_to_string(&config_file)?)?;
 
     println!("sysalert v{}", cargo_crate_version!());
     println!("{config:#?}");
 
-    let s = System::new_all();
-    let hostname = dbg!(s.host_name().unwrap_or_else(|| "unknown".to_string()));
+    let daemon_mode = std::env::args().any(|arg| arg == "--daemon");
+
+    // Gossip is a long-lived failure detector with its own loop; it doesn't
+    // fit a cron-driven one-shot run, which would otherwise block forever
+    // gossiping after its single pass of checks and pile up a fresh
+    // bind-already-in-use failure on every following cron tick.
+    if !config.peers.is_empty() && !daemon_mode {
+        return Err("sysalert: `peers` is configured but --daemon wasn't passed — \
+            gossip needs a long-lived process to run in, so add --daemon \
+            (or clear `peers` to run as a cron-driven one-shot)"
+            .into());
+    }
 
-    let ip_addr = if let Ok(ifas) = list_afinet_netifas() {
-        if let Some((_, ipaddr)) = ifas
-            .iter()
-            .find(|(_, ipaddr)| ipaddr.is_global() && matches!(ipaddr, IpAddr::V4(_)))
-        {
-            format!("{ipaddr:?}")
-        } else {
-            "unknown".to_owned()
-        }
-    } else {
-        "unknown".to_owned()
-    };
+    if daemon_mode {
+        return daemon::run(config_file, config);
+    }
+
+    // Daemon mode wires the metrics exporter into its own worker set (see
+    // daemon::run); standalone one-shot mode still spawns it here since
+    // nothing else will drive it.
+    #[cfg(feature = "metrics")]
+    if config.metrics.is_some() {
+        let config = config.clone();
+        let (hostname, _) = host_identity();
+        std::thread::spawn(move || {
+            if let Err(e) = metrics::run(config, hostname, None) {
+                eprintln!("sysalert: metrics exporter failed: {e}");
+            }
+        });
+    }
+
+    run_checks(&config, &config_file)?;
+
+    Ok(())
+}
+
+/// Runs the one-shot set of threshold/process checks sysalert has always
+/// run under cron, then hands the failures to `state::reconcile`, which
+/// alerts only on checks that are newly failing or due for a re-notify and
+/// sends a separate recovery message for each check that has cleared.
+fn run_checks(config: &Config, config_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let s = System::new_all();
+    let (hostname, ip_addr) = host_identity();
 
     if !config.disable_self_update {
         if let Updated(version) = Update::configure()
@@ -185,28 +348,28 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             }
 
-            send_telegram(&config, message);
+            send_telegram(config, message);
         }
     }
 
-    let mut errors = Vec::new();
+    let mut errors: Vec<(String, String)> = Vec::new();
 
     macro_rules! check_value {
-        ($name:expr, $value:expr, $sign:tt, $threshold:expr) => {
+        ($key:expr, $name:expr, $value:expr, $sign:tt, $threshold:expr) => {
             if $value $sign $threshold {
-                errors.push(format!(
+                errors.push(($key.to_string(), format!(
                     "`{}: {:.4} {} {}`",
                     $name,
                     $value,
                     stringify!($sign),
                     $threshold
-                ));
+                )));
             }
         };
     }
 
     macro_rules! check_running {
-        ($config:expr, $names:expr) => {
+        ($key:expr, $config:expr, $names:expr) => {
             if !$config {
                 let mut is_running = false;
 
@@ -226,16 +389,23 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 if is_running {
                     println!("{} is running", $names.join(", "));
                 } else {
-                    errors.push(format!("`{} is not running`", $names.join(", ")));
+                    errors.push(($key.to_string(), format!("`{} is not running`", $names.join(", "))));
                 }
             }
         };
     }
 
+    // Load average and memory are routinely abnormal while services are
+    // still starting, so downgrade those checks for the first warmup_secs
+    // of uptime rather than firing false positives on every boot.
+    let warmup = dbg!(s.uptime()) < config.warmup_secs;
+
     let system_load_avg = dbg!(s.load_average());
-    check_value!("🚨 load 1", system_load_avg.one, >, config.load_average.one * 2.0);
-    check_value!("load 5", system_load_avg.five, >, config.load_average.five);
-    check_value!("load 15", system_load_avg.fifteen, >, config.load_average.fifteen);
+    check_value!("load1", "🚨 load 1", system_load_avg.one, >, config.load_average.one * 2.0);
+    if !warmup {
+        check_value!("load5", "load 5", system_load_avg.five, >, config.load_average.five);
+        check_value!("load15", "load 15", system_load_avg.fifteen, >, config.load_average.fifteen);
+    }
 
     let disks = dbg!(s.disks());
     for d in disks {
@@ -243,22 +413,26 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         if config.disks.disks.contains(&mount) {
             let perc_free = dbg!(d.available_space() as f64 / d.total_space() as f64);
-            check_value!(mount, perc_free, <, config.disks.minimum);
+            check_value!(format!("disk:{mount}"), mount, perc_free, <, config.disks.minimum);
         }
     }
 
-    let memory_perc_free = if s.available_memory() as f64 == 0.0 {
-        dbg!((s.total_memory() - s.used_memory()) as f64 / s.total_memory() as f64)
-    } else {
-        dbg!(s.available_memory() as f64 / s.total_memory() as f64)
-    };
-    check_value!("memory", memory_perc_free, <, config.memory.minimum);
+    if !warmup {
+        let memory_perc_free = if s.available_memory() as f64 == 0.0 {
+            dbg!((s.total_memory() - s.used_memory()) as f64 / s.total_memory() as f64)
+        } else {
+            dbg!(s.available_memory() as f64 / s.total_memory() as f64)
+        };
+        check_value!("memory", "memory", memory_perc_free, <, config.memory.minimum);
+    }
 
     check_running!(
+        "process:web_server",
         config.process_checks.disable_web_server_check,
         &["apache2", "nginx"]
     );
     check_running!(
+        "process:mysql",
         config.process_checks.disable_mysql_check,
         &["mariadbd", "mysqld"]
     );
@@ -266,11 +440,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     if !config.process_checks.disable_mysql_memory_check {
         for process in s.processes_by_name("mysqld") {
             let process_memory = dbg!(process.memory());
-            check_value!("mysqld", process_memory, >, (s.total_memory() as f64 * 0.75) as u64);
+            check_value!("process:mysqld_memory", "mysqld", process_memory, >, (s.total_memory() as f64 * 0.75) as u64);
         }
         for process in s.processes_by_name("mariadbd") {
             let process_memory = dbg!(process.memory());
-            check_value!("mariadbd", process_memory, >, (s.total_memory() as f64 * 0.75) as u64);
+            check_value!("process:mariadbd_memory", "mariadbd", process_memory, >, (s.total_memory() as f64 * 0.75) as u64);
         }
     }
 
@@ -281,7 +455,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .count();
 
     if b2_processes_count > 1 {
-        errors.push(format!("`b2 is running {} times`", b2_processes_count));
+        errors.push((
+            "process:b2".to_string(),
+            format!("`b2 is running {} times`", b2_processes_count),
+        ));
     }
 
     let backup_file = "/tmp/backup.heartbeat";
@@ -292,29 +469,24 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 if let Ok(time_sec) = time.elapsed() {
                     // If the time is older than 24 hours and 15 minutes, add an alert
                     if time_sec.as_secs() > ((60 * 60 * 24) + (60 * 15)) {
-                        errors.push(format!("`{} has expired`", backup_file));
+                        errors.push(("backup".to_string(), format!("`{} has expired`", backup_file)));
                     }
                 } else {
-                    errors.push(format!("`{}`", "Heartbeat timestamp not supported"));
+                    errors.push(("backup".to_string(), format!("`{}`", "Heartbeat timestamp not supported")));
                 }
             } else {
-                errors.push(format!("`{}`", "Heartbeat timestamp not supported"));
+                errors.push(("backup".to_string(), format!("`{}`", "Heartbeat timestamp not supported")));
             }
         }
-        Err(e) => errors.push(format!("`Heartbeat error: {}`", e)),
+        Err(e) => errors.push(("backup".to_string(), format!("`Heartbeat error: {}`", e))),
     }
 
     let time_mins = 10;
     if s.uptime() < time_mins * 60 {
-        errors.push(format!("rebooted within the last {time_mins}"));
+        errors.push(("boot".to_string(), format!("rebooted within the last {time_mins}")));
     }
 
-    if !errors.is_empty() {
-        send_telegram(
-            &config,
-            format!("❗ `{}` \\- `{}`\n{}", hostname, ip_addr, errors.join("\n")),
-        );
-    }
+    state::reconcile(config, config_path, &hostname, &ip_addr, None, &errors);
 
     Ok(())
 }
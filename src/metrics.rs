@@ -0,0 +1,207 @@
+//! Prometheus-style HTTP metrics exporter, feature-gated behind `metrics`.
+//!
+//! Serves the same values the threshold checks already compute, refreshed
+//! on a fixed interval rather than per scrape so existing monitoring stacks
+//! can poll as often as they like without forcing a fresh `System::new_all()`
+//! on every request. Pairs naturally with `--daemon`; a one-shot run spawns
+//! it too, but it serves only until that run's checks finish and the
+//! process exits.
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde::Deserialize;
+use sysinfo::{DiskExt, ProcessExt, System, SystemExt};
+
+use crate::Config;
+
+pub(crate) const WATCHED_PROCESSES: [&str; 4] = ["apache2", "nginx", "mariadbd", "mysqld"];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub listen: String,
+    #[serde(default = "default_refresh_secs")]
+    pub refresh_secs: u64,
+}
+
+fn default_refresh_secs() -> u64 {
+    15
+}
+
+/// The values `render` serves. Standalone mode fills this in by polling
+/// `sysinfo` on its own `refresh_secs` timer; daemon mode instead hands out
+/// a `SharedGauges` and has each worker update its own fields directly off
+/// the same `sysinfo` call it already makes for its threshold check, so the
+/// exporter adds no polling of its own.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Gauges {
+    pub(crate) load: (f64, f64, f64),
+    pub(crate) disk_free_ratio: Vec<(String, f64)>,
+    pub(crate) memory_free_ratio: f64,
+    pub(crate) process_running: Vec<(&'static str, bool)>,
+    pub(crate) b2_process_count: usize,
+    pub(crate) backup_heartbeat_age_secs: Option<u64>,
+    pub(crate) uptime_secs: u64,
+}
+
+/// A `Gauges` shared between the daemon's per-check workers and the metrics
+/// HTTP server, updated by the former and only ever read by the latter.
+pub(crate) type SharedGauges = Arc<Mutex<Gauges>>;
+
+fn collect(config: &Config) -> Gauges {
+    let mut s = System::new_all();
+    s.refresh_all();
+
+    let load_average = s.load_average();
+
+    let disk_free_ratio = s
+        .disks()
+        .iter()
+        .map(|d| (d.mount_point().to_string_lossy().to_string(), d))
+        .filter(|(mount, _)| config.disks.disks.contains(mount))
+        .map(|(mount, d)| (mount, d.available_space() as f64 / d.total_space() as f64))
+        .collect();
+
+    let memory_free_ratio = if s.available_memory() as f64 == 0.0 {
+        (s.total_memory() - s.used_memory()) as f64 / s.total_memory() as f64
+    } else {
+        s.available_memory() as f64 / s.total_memory() as f64
+    };
+
+    let process_running = WATCHED_PROCESSES
+        .iter()
+        .map(|&name| (name, s.processes_by_name(name).count() > 0))
+        .collect();
+
+    let b2_process_count = s.processes().values().filter(|p| p.name().contains("b2")).count();
+
+    let backup_heartbeat_age_secs = std::fs::metadata("/tmp/backup.heartbeat")
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .map(|elapsed| elapsed.as_secs());
+
+    Gauges {
+        load: (load_average.one, load_average.five, load_average.fifteen),
+        disk_free_ratio,
+        memory_free_ratio,
+        process_running,
+        b2_process_count,
+        backup_heartbeat_age_secs,
+        uptime_secs: s.uptime(),
+    }
+}
+
+fn render(hostname: &str, gauges: &Gauges) -> String {
+    let mut out = String::new();
+
+    out += "# HELP sysalert_load System load average.\n";
+    out += "# TYPE sysalert_load gauge\n";
+    for (period, value) in [("1", gauges.load.0), ("5", gauges.load.1), ("15", gauges.load.2)] {
+        out += &format!("sysalert_load{{period=\"{period}\",host=\"{hostname}\"}} {value}\n");
+    }
+
+    out += "# HELP sysalert_disk_free_ratio Fraction of disk space free.\n";
+    out += "# TYPE sysalert_disk_free_ratio gauge\n";
+    for (mount, ratio) in &gauges.disk_free_ratio {
+        out += &format!("sysalert_disk_free_ratio{{mount=\"{mount}\",host=\"{hostname}\"}} {ratio}\n");
+    }
+
+    out += "# HELP sysalert_memory_free_ratio Fraction of memory free.\n";
+    out += "# TYPE sysalert_memory_free_ratio gauge\n";
+    out += &format!(
+        "sysalert_memory_free_ratio{{host=\"{hostname}\"}} {}\n",
+        gauges.memory_free_ratio
+    );
+
+    out += "# HELP sysalert_process_running Whether a watched process has at least one instance running.\n";
+    out += "# TYPE sysalert_process_running gauge\n";
+    for (name, running) in &gauges.process_running {
+        out += &format!(
+            "sysalert_process_running{{name=\"{name}\",host=\"{hostname}\"}} {}\n",
+            *running as u8
+        );
+    }
+
+    out += "# HELP sysalert_b2_process_count Number of running b2 processes.\n";
+    out += "# TYPE sysalert_b2_process_count gauge\n";
+    out += &format!(
+        "sysalert_b2_process_count{{host=\"{hostname}\"}} {}\n",
+        gauges.b2_process_count
+    );
+
+    out += "# HELP sysalert_backup_heartbeat_age_seconds Age of the backup heartbeat file.\n";
+    out += "# TYPE sysalert_backup_heartbeat_age_seconds gauge\n";
+    if let Some(age) = gauges.backup_heartbeat_age_secs {
+        out += &format!("sysalert_backup_heartbeat_age_seconds{{host=\"{hostname}\"}} {age}\n");
+    }
+
+    out += "# HELP sysalert_uptime_seconds System uptime.\n";
+    out += "# TYPE sysalert_uptime_seconds gauge\n";
+    out += &format!("sysalert_uptime_seconds{{host=\"{hostname}\"}} {}\n", gauges.uptime_secs);
+
+    out
+}
+
+/// Runs the metrics HTTP server forever. A no-op if `config.metrics` isn't
+/// set; intended to be spawned on its own thread.
+///
+/// `shared` is `Some` when daemon mode is already refreshing a `Gauges` off
+/// its own workers — in that case this just serves it. Standalone mode
+/// passes `None` and gets the old behaviour: its own `System::new_all()`
+/// poll on `refresh_secs`.
+pub fn run(
+    config: Config,
+    hostname: String,
+    shared: Option<SharedGauges>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(metrics_config) = config.metrics.clone() else {
+        return Ok(());
+    };
+
+    let listener = TcpListener::bind(&metrics_config.listen)?;
+
+    let gauges = match shared {
+        Some(gauges) => gauges,
+        None => {
+            let gauges = Arc::new(Mutex::new(collect(&config)));
+            let refresh = Duration::from_secs(metrics_config.refresh_secs.max(1));
+            let poll_gauges = Arc::clone(&gauges);
+            thread::spawn(move || loop {
+                thread::sleep(refresh);
+                *poll_gauges.lock().unwrap() = collect(&config);
+            });
+            gauges
+        }
+    };
+
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+
+        // A client that connects without sending a request (a bare
+        // health-check probe, a stalled scraper, a port scan) would
+        // otherwise block this read forever and wedge every later scrape
+        // behind it, since connections are served one at a time.
+        let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+
+        let mut buf = [0u8; 1024];
+        let _ = stream.read(&mut buf);
+
+        let body = render(&hostname, &gauges.lock().unwrap());
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let _ = stream.write_all(response.as_bytes());
+    }
+
+    Ok(())
+}
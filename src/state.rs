@@ -0,0 +1,301 @@
+//! Persisted alert de-duplication.
+//!
+//! A cron-driven run used to rebuild `errors` from scratch every minute, so
+//! a persistent condition re-sent the same Telegram message forever and a
+//! recovery was never announced. This module keeps a small JSON file next
+//! to the config mapping a stable check key to when it started failing, and
+//! only notifies on the transitions: newly failing, overdue for a re-notify,
+//! or recovered.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{send_telegram, Config};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FailingSince {
+    message: String,
+    since: u64,
+    last_notified: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AlertState {
+    #[serde(default)]
+    failing: HashMap<String, FailingSince>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn state_path(config_path: &str) -> PathBuf {
+    let mut path = PathBuf::from(config_path);
+    path.set_extension("state.json");
+    path
+}
+
+fn load(path: &Path) -> AlertState {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+fn save(path: &Path, state: &AlertState) {
+    if let Ok(json) = serde_json::to_string_pretty(state) {
+        if let Err(e) = std::fs::write(path, json) {
+            eprintln!("sysalert: failed to write alert state: {e}");
+        }
+    }
+}
+
+fn format_downtime(secs: u64) -> String {
+    let hours = secs / 3600;
+    let minutes = (secs % 3600) / 60;
+
+    if hours > 0 {
+        format!("{hours}h{minutes}m")
+    } else {
+        format!("{minutes}m")
+    }
+}
+
+/// Diffs `current` (check key, formatted message) against the persisted
+/// state next to `config_path`, sending Telegram alerts only for keys that
+/// are newly failing, due for a per-check re-notification, or have
+/// recovered since the last run.
+///
+/// `scope` limits which previously-failing keys this call is allowed to
+/// declare "recovered". A one-shot `run_checks` run always sees every key at
+/// once, so `None` (everything not in `current` has recovered) is correct.
+/// A daemon worker only ever computes its own handful of keys, so it passes
+/// `Some` with just those keys — otherwise it would see every other
+/// worker's still-failing key missing from its `current` and wrongly
+/// announce it recovered.
+pub fn reconcile(
+    config: &Config,
+    config_path: &str,
+    hostname: &str,
+    ip_addr: &str,
+    scope: Option<&[String]>,
+    current: &[(String, String)],
+) {
+    let path = state_path(config_path);
+    let mut state = load(&path);
+    let now = now();
+
+    let mut newly_failing = Vec::new();
+    let mut due_for_renotify = Vec::new();
+
+    for (key, message) in current {
+        match state.failing.get_mut(key) {
+            Some(existing) => {
+                existing.message = message.clone();
+
+                if let Some(&renotify_after) = config.renotify_after_secs.get(key) {
+                    if now.saturating_sub(existing.last_notified) >= renotify_after {
+                        existing.last_notified = now;
+                        due_for_renotify.push(message.clone());
+                    }
+                }
+            }
+            None => {
+                state.failing.insert(
+                    key.clone(),
+                    FailingSince {
+                        message: message.clone(),
+                        since: now,
+                        last_notified: now,
+                    },
+                );
+                newly_failing.push(message.clone());
+            }
+        }
+    }
+
+    let current_keys: HashSet<&str> = current.iter().map(|(key, _)| key.as_str()).collect();
+    let recovered: Vec<(String, FailingSince)> = state
+        .failing
+        .iter()
+        .filter(|(key, _)| !current_keys.contains(key.as_str()))
+        .filter(|(key, _)| scope.map_or(true, |keys| keys.iter().any(|k| k == *key)))
+        .map(|(key, failing_since)| (key.clone(), failing_since.clone()))
+        .collect();
+
+    for (key, _) in &recovered {
+        state.failing.remove(key);
+    }
+
+    if !newly_failing.is_empty() || !due_for_renotify.is_empty() {
+        let mut lines = newly_failing;
+        lines.extend(due_for_renotify);
+        send_telegram(
+            config,
+            format!("❗ `{hostname}` \\- `{ip_addr}`\n{}", lines.join("\n")),
+        );
+    }
+
+    for (_, failing_since) in recovered {
+        let downtime = format_downtime(now.saturating_sub(failing_since.since));
+        send_telegram(
+            config,
+            format!(
+                "✅ `{hostname}` \\- `{ip_addr}` recovered \\(was down {downtime}\\)\n{}",
+                failing_since.message
+            ),
+        );
+    }
+
+    save(&path, &state);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{default_gossip_bind, default_warmup_secs};
+
+    fn test_config(renotify_after_secs: HashMap<String, u64>) -> Config {
+        Config {
+            telegram_token: String::new(),
+            telegram_chat_id: String::new(),
+            disable_self_update: false,
+            memory: Default::default(),
+            disks: Default::default(),
+            load_average: Default::default(),
+            process_checks: Default::default(),
+            peers: Vec::new(),
+            gossip_bind: default_gossip_bind(),
+            backup: Default::default(),
+            renotify_after_secs,
+            warmup_secs: default_warmup_secs(),
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+
+    /// Each test gets its own state file so tests run in parallel don't
+    /// clobber each other's state.
+    fn test_config_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sysalert-state-test-{name}-{}.toml", std::process::id()))
+    }
+
+    fn read_state(config_path: &PathBuf) -> AlertState {
+        load(&state_path(config_path.to_str().unwrap()))
+    }
+
+    #[test]
+    fn newly_failing_key_is_recorded_and_reported() {
+        let config_path = test_config_path("newly-failing");
+        let config = test_config(HashMap::new());
+
+        reconcile(
+            &config,
+            config_path.to_str().unwrap(),
+            "host",
+            "127.0.0.1",
+            None,
+            &[("disk:/".to_string(), "disk / is low".to_string())],
+        );
+
+        let state = read_state(&config_path);
+        assert!(state.failing.contains_key("disk:/"));
+    }
+
+    #[test]
+    fn cleared_key_is_recorded_as_recovered() {
+        let config_path = test_config_path("recovered");
+        let config = test_config(HashMap::new());
+        let key = ("load5".to_string(), "load5 is high".to_string());
+
+        reconcile(&config, config_path.to_str().unwrap(), "host", "127.0.0.1", None, &[key]);
+        assert!(read_state(&config_path).failing.contains_key("load5"));
+
+        // Nothing failing on the next run: the key should be dropped from
+        // the persisted state as recovered, not left lingering.
+        reconcile(&config, config_path.to_str().unwrap(), "host", "127.0.0.1", None, &[]);
+        assert!(!read_state(&config_path).failing.contains_key("load5"));
+    }
+
+    #[test]
+    fn still_failing_key_is_not_renotified_without_interval() {
+        let config_path = test_config_path("no-renotify");
+        let config = test_config(HashMap::new());
+        let key = ("process:mysqld".to_string(), "mysqld is not running".to_string());
+
+        reconcile(&config, config_path.to_str().unwrap(), "host", "127.0.0.1", None, &[key.clone()]);
+        let first_notified = read_state(&config_path).failing["process:mysqld"].last_notified;
+
+        reconcile(&config, config_path.to_str().unwrap(), "host", "127.0.0.1", None, &[key]);
+        let second_notified = read_state(&config_path).failing["process:mysqld"].last_notified;
+
+        assert_eq!(first_notified, second_notified);
+    }
+
+    #[test]
+    fn still_failing_key_is_renotified_once_interval_elapses() {
+        let config_path = test_config_path("renotify");
+        let mut renotify_after_secs = HashMap::new();
+        renotify_after_secs.insert("process:mysqld".to_string(), 0);
+        let config = test_config(renotify_after_secs);
+        let key = ("process:mysqld".to_string(), "mysqld is not running".to_string());
+
+        reconcile(&config, config_path.to_str().unwrap(), "host", "127.0.0.1", None, &[key.clone()]);
+        let first_notified = read_state(&config_path).failing["process:mysqld"].last_notified;
+
+        // Back-date it so the zero-second interval is unambiguously overdue
+        // regardless of how fast the two reconcile calls race each other.
+        let path = state_path(config_path.to_str().unwrap());
+        let mut state = load(&path);
+        state.failing.get_mut("process:mysqld").unwrap().last_notified -= 1;
+        save(&path, &state);
+
+        reconcile(&config, config_path.to_str().unwrap(), "host", "127.0.0.1", None, &[key]);
+        let second_notified = read_state(&config_path).failing["process:mysqld"].last_notified;
+
+        assert!(second_notified >= first_notified);
+    }
+
+    #[test]
+    fn recovery_scope_ignores_keys_outside_this_workers_scope() {
+        // A daemon worker only ever reconciles its own handful of keys; a
+        // still-failing key owned by a *different* worker must not be
+        // treated as recovered just because it's absent from this call's
+        // `current`.
+        let config_path = test_config_path("scoped-recovery");
+        let config = test_config(HashMap::new());
+
+        reconcile(
+            &config,
+            config_path.to_str().unwrap(),
+            "host",
+            "127.0.0.1",
+            None,
+            &[
+                ("load5".to_string(), "load5 is high".to_string()),
+                ("memory".to_string(), "memory is low".to_string()),
+            ],
+        );
+
+        // This worker only owns "load5" and sees it clear; "memory" isn't in
+        // its scope at all, so it must survive untouched.
+        reconcile(
+            &config,
+            config_path.to_str().unwrap(),
+            "host",
+            "127.0.0.1",
+            Some(&["load5".to_string()]),
+            &[],
+        );
+
+        let state = read_state(&config_path);
+        assert!(!state.failing.contains_key("load5"));
+        assert!(state.failing.contains_key("memory"));
+    }
+}
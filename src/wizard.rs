@@ -0,0 +1,250 @@
+//! Interactive first-run setup.
+//!
+//! Hand-writing `sysalert.toml` is the only way to configure sysalert today,
+//! and `#[serde(deny_unknown_fields)]` makes a single typo a cryptic fatal
+//! error. `--wizard` prompts for the essentials, verifies the Telegram
+//! credentials with a real test message, and auto-detects disks/CPUs to
+//! propose sensible defaults. `--check-config` parses the file in isolation
+//! and prints a friendlier diagnostic, including a "did you mean" guess for
+//! a rejected unknown field.
+
+use std::io::Write;
+
+use sysinfo::{DiskExt, System, SystemExt};
+
+use crate::{default_warmup_secs, send_telegram, Backup, Config, Disks, LoadAverage, Memory, ProcessChecks};
+
+fn prompt(question: &str) -> String {
+    print!("{question}");
+    let _ = std::io::stdout().flush();
+
+    let mut line = String::new();
+    let _ = std::io::stdin().read_line(&mut line);
+    line.trim().to_string()
+}
+
+fn prompt_yes_no(question: &str, default_yes: bool) -> bool {
+    let suffix = if default_yes { "[Y/n]" } else { "[y/N]" };
+    let answer = prompt(&format!("{question} {suffix} "));
+
+    if answer.is_empty() {
+        default_yes
+    } else {
+        matches!(answer.to_lowercase().as_str(), "y" | "yes")
+    }
+}
+
+fn build_test_config(telegram_token: String, telegram_chat_id: String) -> Config {
+    Config {
+        telegram_token,
+        telegram_chat_id,
+        disable_self_update: true,
+        memory: Memory::default(),
+        disks: Disks::default(),
+        load_average: LoadAverage::default(),
+        process_checks: ProcessChecks::default(),
+        peers: Vec::new(),
+        gossip_bind: "0.0.0.0:7946".to_string(),
+        backup: Backup::default(),
+        renotify_after_secs: Default::default(),
+        warmup_secs: default_warmup_secs(),
+        #[cfg(feature = "metrics")]
+        metrics: None,
+    }
+}
+
+/// Runs the interactive setup wizard and writes a fully-commented TOML file
+/// to `config_path`.
+pub fn run(config_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    println!("sysalert setup wizard");
+    println!("======================");
+    println!("This will write a new config to {config_path}.\n");
+
+    let telegram_token = prompt("Telegram bot token: ");
+    let telegram_chat_id = prompt("Telegram chat id: ");
+
+    let test_config = build_test_config(telegram_token.clone(), telegram_chat_id.clone());
+    println!("Sending a test message via Telegram...");
+    send_telegram(
+        &test_config,
+        "✅ sysalert wizard: this host is now configured".to_string(),
+    );
+
+    if !prompt_yes_no("Did the test message arrive?", true) {
+        eprintln!(
+            "warning: double check telegram_token/telegram_chat_id in {config_path} before relying on alerts"
+        );
+    }
+
+    let mut s = System::new_all();
+    s.refresh_disks_list();
+    s.refresh_cpu();
+
+    let detected_disks: Vec<String> = s
+        .disks()
+        .iter()
+        .map(|d| d.mount_point().to_string_lossy().to_string())
+        .collect();
+    let disks = if detected_disks.is_empty() {
+        vec!["/".to_string()]
+    } else {
+        println!("Detected mounted disks: {}", detected_disks.join(", "));
+        detected_disks
+    };
+
+    let cpu_count = s.cpus().len().max(1) as f64;
+    println!("Detected {cpu_count} CPU(s); using that as the load average baseline.");
+
+    let enable_web_server_check = prompt_yes_no("Monitor apache2/nginx?", true);
+    let enable_mysql_check = prompt_yes_no("Monitor mariadbd/mysqld?", true);
+    let enable_mysql_memory_check = enable_mysql_check
+        && prompt_yes_no("Also alert if mysql/mariadb uses over 75% of memory?", true);
+
+    let toml = render_toml(
+        &telegram_token,
+        &telegram_chat_id,
+        &disks,
+        cpu_count,
+        enable_web_server_check,
+        enable_mysql_check,
+        enable_mysql_memory_check,
+    );
+    std::fs::write(config_path, toml)?;
+
+    println!("\nWrote {config_path}. Re-run with --check-config any time to validate it.");
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn render_toml(
+    telegram_token: &str,
+    telegram_chat_id: &str,
+    disks: &[String],
+    cpu_count: f64,
+    enable_web_server_check: bool,
+    enable_mysql_check: bool,
+    enable_mysql_memory_check: bool,
+) -> String {
+    let disks_list = disks
+        .iter()
+        .map(|d| format!("\"{d}\""))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(
+        r#"# Written by `sysalert --wizard`. See https://github.com/daniellockyer/sysalert
+# for the full list of options; anything left out here falls back to its
+# default.
+
+telegram_token = "{telegram_token}"
+telegram_chat_id = "{telegram_chat_id}"
+
+# Skip the load 5/15 and memory checks for this many seconds after boot (or
+# a detected resume from suspend), since they're routinely abnormal while
+# services are still starting. Load 1 is still checked.
+warmup_secs = 300
+
+[load_average]
+# Baseline load average, auto-detected from CPU count. `one` alerts at 2x
+# this value; `five`/`fifteen` alert above it directly.
+one = {cpu_count}
+five = {cpu_count}
+fifteen = {cpu_count}
+
+[disks]
+# Mount points to watch, auto-detected from this host.
+disks = [{disks_list}]
+# Alert when free space drops below this fraction.
+minimum = 0.05
+
+[memory]
+# Alert when free memory drops below this fraction.
+minimum = 0.05
+
+[process_checks]
+disable_web_server_check = {disable_web_server_check}
+disable_mysql_check = {disable_mysql_check}
+disable_mysql_memory_check = {disable_mysql_memory_check}
+"#,
+        disable_web_server_check = !enable_web_server_check,
+        disable_mysql_check = !enable_mysql_check,
+        disable_mysql_memory_check = !enable_mysql_memory_check,
+    )
+}
+
+/// Parses `config_path` on its own and prints a field-level diagnostic
+/// instead of the raw error `main` would otherwise bail out with.
+pub fn check_config(config_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let contents = match std::fs::read_to_string(config_path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            println!("could not read {config_path}: {e}");
+            return Ok(());
+        }
+    };
+
+    match toml::from_str::<Config>(&contents) {
+        Ok(config) => {
+            println!("{config_path} is valid:");
+            println!("{config:#?}");
+        }
+        Err(e) => {
+            println!("{config_path} failed to parse:");
+            println!("  {e}");
+
+            if let Some(suggestion) = suggest_field(&e.to_string()) {
+                println!("  did you mean `{suggestion}`?");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Pulls an unknown-field name and its list of valid alternatives out of a
+/// `deny_unknown_fields` error message and, if one is close enough, suggests
+/// the most likely intended field.
+fn suggest_field(error_message: &str) -> Option<String> {
+    let unknown = backtick_after(error_message, "unknown field ")?;
+    let candidates = all_backticks(error_message.split("expected one of").nth(1)?);
+
+    candidates
+        .into_iter()
+        .map(|candidate| (levenshtein(&unknown, &candidate), candidate))
+        .min_by_key(|(distance, _)| *distance)
+        .filter(|(distance, _)| *distance <= 3)
+        .map(|(_, candidate)| candidate)
+}
+
+fn backtick_after(haystack: &str, marker: &str) -> Option<String> {
+    let after_marker = haystack.split(marker).nth(1)?;
+    all_backticks(after_marker).into_iter().next()
+}
+
+fn all_backticks(s: &str) -> Vec<String> {
+    s.split('`')
+        .skip(1)
+        .step_by(2)
+        .map(String::from)
+        .collect()
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.iter().enumerate() {
+        let mut prev_diag = row[0];
+        row[0] = i + 1;
+
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            let new_value = (row[j + 1] + 1).min(row[j] + 1).min(prev_diag + cost);
+            prev_diag = row[j + 1];
+            row[j + 1] = new_value;
+        }
+    }
+
+    row[b.len()]
+}